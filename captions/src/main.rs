@@ -1,13 +1,46 @@
 use itertools::Itertools;
+use serde::Deserialize;
 use std::{
     collections::{HashMap, HashSet},
     env,
     io::{self, BufRead},
     str::FromStr,
 };
-use tracing::{debug, error, info, Level};
+use tracing::{debug, info, Level};
 use tracing_subscriber::fmt::format::FmtSpan;
 
+#[path = "../../common/src/lib.rs"]
+mod common_config;
+
+/// Pipeline settings loaded from an optional `--config <path>` TOML file.
+/// Any field left unset in the file falls back to its default.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Config {
+    /// k-means branch factor: how many clusters each split produces.
+    num_clusters: usize,
+    /// Stop splitting once a group is at or below this many files.
+    recursion_threshold: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            num_clusters: 2,
+            recursion_threshold: 100,
+        }
+    }
+}
+
+/// Splits `--config <path>` out of the process args, returning the loaded
+/// config (or defaults) alongside the remaining positional arguments (the
+/// caption files to load). Kept on `args_os()`/`OsString` so a non-UTF-8
+/// input path doesn't crash the process — only the `--config` value itself
+/// needs to be valid UTF-8, to be read as a TOML file path.
+fn parse_args() -> (Config, Vec<std::ffi::OsString>) {
+    common_config::parse_args(env::args_os().skip(1))
+}
+
 pub fn log_init() {
     // install global collector configured based on RUST_LOG env var.
     let level =
@@ -66,9 +99,12 @@ fn kmeans(
 fn main() -> io::Result<()> {
     log_init();
 
+    let (config, inputs) = parse_args();
+    info!(config = debug(&config), "loaded config");
+
     let mut known_words = HashMap::<String, usize>::new();
     let mut file_word_bag = Vec::new();
-    for filename in env::args_os().skip(1) {
+    for filename in inputs {
         let file = std::fs::File::open(filename)?;
 
         let reader = std::io::BufReader::new(file);
@@ -95,7 +131,7 @@ fn main() -> io::Result<()> {
     let mut pending = Vec::new();
     pending.push(file_word_bag);
     while let Some(bag) = pending.pop() {
-        if bag.len() <= 100 {
+        if bag.len() <= config.recursion_threshold {
             idx += 1;
             info!(
                 idx,
@@ -106,7 +142,7 @@ fn main() -> io::Result<()> {
             continue;
         }
         debug!(file_count = bag.len(), "split");
-        pending.append(&mut kmeans(&bag, &known_words, 2));
+        pending.append(&mut kmeans(&bag, &known_words, config.num_clusters));
     }
     Ok(())
 }
@@ -0,0 +1,53 @@
+//! `--config <path>` parsing shared by both the `captions` and `tags`
+//! binaries. Each binary has its own `Config` type (different fields,
+//! different defaults) but loads it the same way, so that plumbing lives
+//! here instead of being copy-pasted per binary.
+//!
+//! This tree has no crate manifest for `common`, `captions`, or `tags` (it's
+//! a source-only snapshot), so this can't be pulled in as a real path
+//! dependency yet. It lives under `src/lib.rs` so that, once a workspace
+//! `Cargo.toml` is added, the only change needed is swapping the `#[path]`
+//! include in each binary for `common = { path = "../common" }` plus
+//! `use common::...`.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+
+/// Loads and deserializes a TOML config file at `path`.
+pub fn load<T: serde::de::DeserializeOwned>(path: &str) -> io::Result<T> {
+    let text = std::fs::read_to_string(path)?;
+    toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Splits `--config <path>` out of `args`, returning the loaded config (or
+/// defaults, if absent or unreadable) alongside the remaining positional
+/// arguments, untouched. Only the `--config` value itself is lossily
+/// converted to UTF-8, since it has to be loaded as a file path string;
+/// everything else passes through as `OsString` so a non-UTF-8 input
+/// filename doesn't crash the process.
+pub fn parse_args<T>(mut args: impl Iterator<Item = OsString>) -> (T, Vec<OsString>)
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    let mut config = None;
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == OsStr::new("--config") {
+            match args.next() {
+                Some(path) => {
+                    let path = path.to_string_lossy().into_owned();
+                    match load::<T>(&path) {
+                        Ok(c) => config = Some(c),
+                        Err(err) => tracing::error!(path, %err, "failed to load config, using defaults"),
+                    }
+                }
+                None => tracing::error!("--config requires a path argument"),
+            }
+        } else {
+            files.push(arg);
+        }
+    }
+
+    (config.unwrap_or_default(), files)
+}
@@ -7,11 +7,78 @@ use std::{
     str::FromStr,
 };
 
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term::{
+    self,
+    termcolor::{ColorChoice, StandardStream},
+};
 use itertools::Itertools;
 use kmedoids::ArrayAdapter;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tracing::{debug, error, info, instrument, Level};
 use tracing_subscriber::fmt::format::FmtSpan;
 
+#[path = "../../common/src/lib.rs"]
+mod common_config;
+
+/// What the copy phase should actually do to the filesystem.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Action {
+    /// Log every intended copy and duplicate link without touching the
+    /// filesystem. No removes are logged, since nothing is moved.
+    #[default]
+    DryRun,
+    Copy,
+    Move,
+}
+
+/// Pipeline settings loaded from an optional `--config <path>` TOML file.
+/// Any field left unset in the file falls back to its default.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Config {
+    /// Aim for this many files per cluster when choosing `k` for `fasterpam`.
+    files_per_cluster: usize,
+    /// Destination directory template; `{id}` is replaced with the cluster id.
+    output_dir: String,
+    action: Action,
+    /// On-disk cache of per-file-hash tag vectors, reused across runs.
+    cache_dir: String,
+    /// MIME types the pipeline will accept as input images; anything else is
+    /// filtered out before clustering and re-checked at copy time.
+    allowed_mime_types: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            files_per_cluster: 50,
+            output_dir: "tag_partitioned/group_{id}".to_string(),
+            action: Action::default(),
+            cache_dir: ".caption_cluster_cache".to_string(),
+            allowed_mime_types: vec!["image/png".to_string(), "image/jpeg".to_string()],
+        }
+    }
+}
+
+/// Splits `--config <path>` out of the process args, returning the loaded
+/// config (or defaults, with a dry-run preview) alongside the remaining
+/// positional arguments (the tag files to load). Tag files are converted
+/// from `OsString` to `String` here (lossily, same as the `--config` path)
+/// since `load_tags` formats them into diagnostic source names.
+fn parse_args() -> (Config, Vec<String>) {
+    let (config, files) = common_config::parse_args(env::args_os().skip(1));
+    let files = files
+        .into_iter()
+        .map(|f| f.to_string_lossy().into_owned())
+        .collect();
+    (config, files)
+}
+
 pub fn log_init() {
     // install global collector configured based on RUST_LOG env var.
     let level =
@@ -30,72 +97,312 @@ pub fn log_init() {
 struct TaggedFile {
     filename: String,
     tags: HashMap<String, f64>,
+    /// `sha256` of the image's bytes at load time, if the image was
+    /// readable. Reused by `dedup_by_content` so nothing has to re-hash.
+    content_hash: Option<[u8; 32]>,
+}
+
+/// A tag vector cached on disk, keyed by an image's content hash. `source`
+/// is the raw tag-file text it was parsed from: if a later run sees the
+/// same image bytes but a different `source` (the caption/tag line was
+/// edited), the entry is stale and gets reparsed rather than reused.
+#[derive(Serialize, Deserialize)]
+struct CachedTags {
+    source: String,
+    tags: HashMap<String, f64>,
+}
+/// Diagnostics collector shared across a run: adds one `codespan_reporting`
+/// source file (the offending line) per reported diagnostic so malformed lines can be
+/// reported with a labeled span rather than aborting the whole process.
+struct Diagnostics {
+    files: SimpleFiles<String, String>,
+    writer: StandardStream,
+    config: term::Config,
+}
+
+impl Diagnostics {
+    fn new() -> Self {
+        Self {
+            files: SimpleFiles::new(),
+            writer: StandardStream::stderr(ColorChoice::Auto),
+            config: term::Config::default(),
+        }
+    }
+
+    fn report(&mut self, source_name: &str, line: &str, diagnostic: &Diagnostic<usize>) {
+        let file_id = self.files.add(source_name.to_string(), line.to_string());
+        let diagnostic = diagnostic.clone().with_labels(
+            diagnostic
+                .labels
+                .iter()
+                .map(|l| Label::new(l.style, file_id, l.range.clone()))
+                .collect(),
+        );
+        if let Err(err) = term::emit(&mut self.writer.lock(), &self.config, &self.files, &diagnostic)
+        {
+            error!(%err, "failed to emit diagnostic");
+        }
+    }
 }
-fn load_tags(filename: &str) -> io::Result<Vec<TaggedFile>> {
+
+fn load_tags(
+    filename: &str,
+    diagnostics: &mut Diagnostics,
+    cache: &sled::Db,
+) -> io::Result<Vec<TaggedFile>> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
 
-    let tagged_files = reader
-        .lines()
-        .flatten()
-        .map(|line| {
-            let (_, image, tags, ratings) = line.split('\t').collect_tuple().unwrap();
-            let mut tags = tags
-                .split(',')
-                .filter(|t| !t.is_empty())
-                .flat_map(Tag::from_str)
-                .fold(HashMap::new(), |mut acc, tag| {
-                    acc.insert(tag.name, tag.score);
-                    acc
-                });
-            // Normalize
-            let len = tags.values().map(|v| v * v).sum::<f64>().sqrt();
-            for (_, v) in tags.iter_mut() {
-                *v /= len;
+    let mut tagged_files = Vec::new();
+    let mut cache_hits = 0usize;
+
+    for (line_no, line) in reader.lines().map_while(Result::ok).enumerate() {
+        let source_name = format!("{filename}:{}", line_no + 1);
+
+        let Some((_, image, tags_field, ratings)) = line.split('\t').collect_tuple() else {
+            diagnostics.report(
+                &source_name,
+                &line,
+                &Diagnostic::error()
+                    .with_message("expected 4 tab-separated fields: index, image, tags, ratings")
+                    .with_labels(vec![Label::primary(0, 0..line.len())]),
+            );
+            continue;
+        };
+
+        // Hash the image up front (cheap relative to the regex-per-tag parse
+        // below) so an unchanged image whose tag text also hasn't changed
+        // can skip straight to the cached vector.
+        let content_hash = std::fs::read(image).ok().map(|bytes| {
+            let mut hasher = Sha256::new();
+            hasher.update(&bytes);
+            let digest: [u8; 32] = hasher.finalize().into();
+            digest
+        });
+
+        if let Some(hash) = content_hash {
+            if let Ok(Some(cached)) = cache.get(hash) {
+                if let Ok(cached) = bincode::deserialize::<CachedTags>(&cached) {
+                    if cached.source == tags_field {
+                        cache_hits += 1;
+                        tagged_files.push(TaggedFile {
+                            filename: image.to_string(),
+                            tags: cached.tags,
+                            content_hash,
+                        });
+                        continue;
+                    }
+                }
             }
-            debug!(image, tags = debug(&tags), ratings);
-            TaggedFile {
-                filename: image.to_string(),
-                tags,
+        }
+
+        let mut tags = HashMap::new();
+        let mut offset = tags_field.as_ptr() as usize - line.as_ptr() as usize;
+        for tag_str in tags_field.split(',') {
+            let span_start = offset;
+            offset += tag_str.len() + 1; // +1 for the consumed ','
+            if tag_str.is_empty() {
+                continue;
             }
-        })
-        .filter(|t| !t.tags.is_empty())
-        .collect_vec();
+            match Tag::from_str(tag_str) {
+                Ok(tag) => {
+                    tags.insert(tag.name, tag.score);
+                }
+                Err(span) => {
+                    let absolute = (span_start + span.start)..(span_start + span.end);
+                    diagnostics.report(
+                        &source_name,
+                        &line,
+                        &Diagnostic::error()
+                            .with_message("malformed tag, expected `(name:score)`")
+                            .with_labels(vec![Label::primary(0, absolute)]),
+                    );
+                }
+            }
+        }
+        if tags.is_empty() {
+            continue;
+        }
+
+        // Normalize
+        let len = tags.values().map(|v| v * v).sum::<f64>().sqrt();
+        for (_, v) in tags.iter_mut() {
+            *v /= len;
+        }
+        debug!(image, tags = debug(&tags), ratings);
 
+        if let Some(hash) = content_hash {
+            let cached = CachedTags {
+                source: tags_field.to_string(),
+                tags: tags.clone(),
+            };
+            match bincode::serialize(&cached) {
+                Ok(bytes) => {
+                    if let Err(err) = cache.insert(hash, bytes) {
+                        error!(image, %err, "failed to cache tags");
+                    }
+                }
+                Err(err) => error!(image, %err, "failed to encode tags for cache"),
+            }
+        }
+
+        tagged_files.push(TaggedFile {
+            filename: image.to_string(),
+            tags,
+            content_hash,
+        });
+    }
+
+    info!(cache_hits, file_count = tagged_files.len(), filename, "tag cache lookups");
     Ok(tagged_files)
 }
 
+/// Sniffs a file's MIME type from its content. Returns `None` only when the
+/// file can't be read; any other file yields whatever type `tree_magic_mini`
+/// recognizes from its bytes, image or not. Only the leading bytes decide
+/// the type, so a truncated image whose header is intact still sniffs as its
+/// image type. We deliberately do not fall back to extension guessing here: a `.png`-named
+/// text file or a zero-byte download must fail validation, not pass it
+/// based on its filename alone.
+fn sniff_mime(path: &str) -> Option<String> {
+    tree_magic_mini::from_filepath(std::path::Path::new(path)).map(|m| m.to_string())
+}
+
+/// Whether `path` sniffs as one of `allowed`. Used both to filter the input
+/// set before clustering and to re-validate at copy time.
+fn is_allowed_image(path: &str, allowed: &[String]) -> bool {
+    match sniff_mime(path) {
+        Some(mime) => allowed.contains(&mime),
+        None => {
+            debug!(path, "content sniff failed, rejecting as non-image");
+            false
+        }
+    }
+}
+
+/// Collapses images with identical content into a single representative
+/// `TaggedFile`, keyed on the `content_hash` computed once in `load_tags`.
+/// Returns the deduplicated files plus a map from each surviving
+/// representative's path to the duplicate paths it absorbed, so the copy
+/// phase can hard-link or skip the extras instead of re-copying bytes.
+fn dedup_by_content(tagged_files: Vec<TaggedFile>) -> (Vec<TaggedFile>, HashMap<String, Vec<String>>) {
+    let mut by_hash: HashMap<[u8; 32], usize> = HashMap::new();
+    let mut deduped: Vec<TaggedFile> = Vec::new();
+    let mut duplicates: HashMap<String, Vec<String>> = HashMap::new();
+
+    for file in tagged_files {
+        let Some(hash) = file.content_hash else {
+            deduped.push(file);
+            continue;
+        };
+
+        match by_hash.get(&hash) {
+            Some(&existing) => {
+                debug!(
+                    duplicate = file.filename,
+                    original = deduped[existing].filename,
+                    "duplicate image content"
+                );
+                duplicates
+                    .entry(deduped[existing].filename.clone())
+                    .or_default()
+                    .push(file.filename);
+            }
+            None => {
+                by_hash.insert(hash, deduped.len());
+                deduped.push(file);
+            }
+        }
+    }
+
+    (deduped, duplicates)
+}
+
 #[derive(Debug)]
 struct Tag {
     name: String,
     score: f64,
 }
 impl FromStr for Tag {
-    type Err = std::convert::Infallible;
+    /// The byte range within the original (untrimmed) tag text that failed
+    /// to parse, for use in a `codespan_reporting` label.
+    type Err = std::ops::Range<usize>;
 
     fn from_str(t: &str) -> std::result::Result<Self, <Self as std::str::FromStr>::Err> {
-        info!(t, "to_tags");
-        let t = t.trim();
+        debug!(t, "to_tags");
+        let trimmed = t.trim();
+        let leading = t.len() - t.trim_start().len();
+
         let r = regex::Regex::new(r"^\((?P<name>.+):(?P<score>-?[0-9\.]+)\)$").unwrap();
-        let c = r.captures(t).unwrap();
+        let Some(c) = r.captures(trimmed) else {
+            return Err(leading..t.len());
+        };
         let name = c.name("name").unwrap().as_str().to_string();
-        let score = c.name("score").unwrap().as_str().parse().unwrap();
-        let tag = Self { name, score };
-        Ok(tag)
+        let Ok(score) = c.name("score").unwrap().as_str().parse() else {
+            return Err(leading..t.len());
+        };
+        Ok(Self { name, score })
     }
 }
 
-struct Dissim<'a> {
-    tagged_files: &'a [TaggedFile],
+/// Full symmetric dissimilarity matrix, materialized once up front so that
+/// `fasterpam`'s O(n^2) calls to `get` are flat-`Vec` lookups instead of
+/// recomputing a cosine dissimilarity from scratch every time.
+struct PrecomputedDissim {
+    n: usize,
+    matrix: Vec<f64>,
 }
-impl<'a> Dissim<'a> {
-    fn new(tagged_files: &'a [TaggedFile]) -> Self {
-        Self { tagged_files }
+
+impl PrecomputedDissim {
+    /// Builds the matrix via an inverted index: each tag is mapped to the
+    /// `(file_index, normalized_score)` pairs that contain it, so only file
+    /// pairs that actually share a tag are touched, rather than all n^2
+    /// pairs. The per-tag accumulation is parallelized with `rayon`.
+    fn new(tagged_files: &[TaggedFile]) -> Self {
+        let n = tagged_files.len();
+
+        let mut postings: HashMap<&str, Vec<(usize, f64)>> = HashMap::new();
+        for (idx, file) in tagged_files.iter().enumerate() {
+            for (name, score) in &file.tags {
+                postings.entry(name.as_str()).or_default().push((idx, *score));
+            }
+        }
+
+        let similarity: HashMap<(usize, usize), f64> = postings
+            .par_iter()
+            .fold(HashMap::new, |mut acc, (_, posting)| {
+                for (a, &(i, si)) in posting.iter().enumerate() {
+                    for &(j, sj) in &posting[a + 1..] {
+                        let key = if i < j { (i, j) } else { (j, i) };
+                        *acc.entry(key).or_insert(0.0) += si * sj;
+                    }
+                }
+                acc
+            })
+            .reduce(HashMap::new, |mut acc, other| {
+                for (key, sim) in other {
+                    *acc.entry(key).or_insert(0.0) += sim;
+                }
+                acc
+            });
+
+        let mut matrix = vec![1.0; n * n];
+        for (i, row) in matrix.chunks_exact_mut(n).enumerate() {
+            row[i] = 0.0;
+        }
+        for ((i, j), sim) in similarity {
+            let dissim = 1.0 - sim;
+            matrix[i * n + j] = dissim;
+            matrix[j * n + i] = dissim;
+        }
+
+        Self { n, matrix }
     }
 }
-impl<'a> ArrayAdapter<f64> for Dissim<'a> {
+
+impl ArrayAdapter<f64> for PrecomputedDissim {
     fn len(&self) -> usize {
-        self.tagged_files.len()
+        self.n
     }
 
     fn is_square(&self) -> bool {
@@ -103,42 +410,17 @@ impl<'a> ArrayAdapter<f64> for Dissim<'a> {
     }
 
     fn get(&self, x: usize, y: usize) -> f64 {
-        let tags_x = &self.tagged_files[x].tags;
-        debug!(x, tags_x = debug(tags_x));
-
-        let tags_y = &self.tagged_files[y].tags;
-        debug!(y, tags_y = debug(tags_y));
-
-        let similarity: f64 = tags_x
-            .keys()
-            .chain(tags_y.keys())
-            .cloned()
-            .unique()
-            .map(|key| tags_x.get(&key).unwrap_or(&0.) * tags_y.get(&key).unwrap_or(&0.))
-            .sum();
-
-        debug!(x, y, similarity);
-
-        if x == y && (1.0 - similarity).abs() > 1e-3 {
-            error!(
-                x,
-                tags_x = debug(tags_x),
-                y,
-                tags_y = debug(tags_y),
-                similarity
-            );
-            panic!();
-        }
-
-        1. - similarity
+        self.matrix[x * self.n + y]
     }
 }
 
 #[instrument(skip(tagged_files))]
-fn cut(tagged_files: &[TaggedFile]) -> HashMap<usize, (HashSet<String>, HashSet<String>)> {
-    // Aim for ~50 files per cluster
-    let k = tagged_files.len() / 50;
-    let mat = Dissim::new(tagged_files);
+fn cut(
+    tagged_files: &[TaggedFile],
+    files_per_cluster: usize,
+) -> HashMap<usize, (HashSet<String>, HashSet<String>)> {
+    let k = tagged_files.len() / files_per_cluster;
+    let mat = PrecomputedDissim::new(tagged_files);
     let mut meds = kmedoids::random_initialization(tagged_files.len(), k, &mut rand::thread_rng());
     let r: (f64, Vec<usize>, usize, usize) = kmedoids::fasterpam(&mat, &mut meds, k);
 
@@ -163,11 +445,33 @@ fn cut(tagged_files: &[TaggedFile]) -> HashMap<usize, (HashSet<String>, HashSet<
 fn main() -> io::Result<()> {
     log_init();
 
-    let tagged_files = std::env::args()
-        .skip(1)
-        .flat_map(|f| load_tags(&f))
+    let (config, inputs) = parse_args();
+    info!(config = debug(&config), "loaded config");
+
+    let cache = sled::open(&config.cache_dir).map_err(|err| {
+        io::Error::other(format!(
+            "failed to open tag cache at {}: {err}",
+            config.cache_dir
+        ))
+    })?;
+
+    let mut diagnostics = Diagnostics::new();
+    let tagged_files = inputs
+        .iter()
+        .flat_map(|f| load_tags(f, &mut diagnostics, &cache))
         .flatten()
         .collect_vec();
+    if let Err(err) = cache.flush() {
+        error!(%err, "failed to persist tag cache");
+    }
+
+    let (tagged_files, duplicate_paths) = dedup_by_content(tagged_files);
+    let (tagged_files, non_images): (Vec<_>, Vec<_>) = tagged_files
+        .into_iter()
+        .partition(|f| is_allowed_image(&f.filename, &config.allowed_mime_types));
+    for file in &non_images {
+        info!(file = file.filename, "skipping input that isn't a recognized image");
+    }
 
     let mut tag_count: HashMap<String, usize> = HashMap::new();
     for tagged_file in &tagged_files {
@@ -177,32 +481,175 @@ fn main() -> io::Result<()> {
     }
     info!(tag_count = debug(tag_count), "counts");
     //info!(tagged_files = debug(&tagged_files), "tagged files");
-    let partition = cut(&tagged_files);
+    let partition = cut(&tagged_files, config.files_per_cluster);
 
-    let write_files = true;
-    let move_files = true;
     for (k, (files, v)) in partition {
-        let outdir = PathBuf::from(format!("tag_partitioned/group_{k}"));
+        let outdir = PathBuf::from(config.output_dir.replace("{id}", &k.to_string()));
 
         for file in &files {
             let to = outdir.join(PathBuf::from(file).file_name().unwrap());
             debug!(k, files = debug(&files), tags = debug(&v));
 
+            if config.action == Action::DryRun {
+                info!("dry-run: would copy {file} -> {to:?}");
+                for dup in duplicate_paths.get(file).into_iter().flatten() {
+                    let dup_to = outdir.join(PathBuf::from(dup).file_name().unwrap());
+                    info!("dry-run: would link {dup} -> {dup_to:?}");
+                }
+                continue;
+            }
+
+            if !is_allowed_image(file, &config.allowed_mime_types) {
+                error!(file, "no longer a recognized image at copy time, skipping");
+                continue;
+            }
+
             info!("copy {file} -> {to:?}");
-            if write_files {
-                std::fs::create_dir_all(&outdir)?;
-
-                // Assume error means no such file
-                if std::fs::metadata(&to).is_err() {
-                    if std::fs::copy(file, to).is_ok() {
-                        if move_files {
-                            info!("remove {file}");
-                            std::fs::remove_file(file)?;
-                        }
-                    }
+            std::fs::create_dir_all(&outdir)?;
+
+            // Assume error means no such file
+            if std::fs::metadata(&to).is_err()
+                && std::fs::copy(file, &to).is_ok()
+                && config.action == Action::Move
+            {
+                info!("remove {file}");
+                std::fs::remove_file(file)?;
+            }
+
+            // Duplicates of this file's content never went through clustering
+            // or copying themselves, so hard-link them to the copy we just made
+            // instead of re-reading and re-copying their bytes.
+            for dup in duplicate_paths.get(file).into_iter().flatten() {
+                let dup_to = outdir.join(PathBuf::from(dup).file_name().unwrap());
+                info!("link {dup} -> {dup_to:?}");
+                if std::fs::metadata(&dup_to).is_err()
+                    && std::fs::hard_link(&to, &dup_to).is_ok()
+                    && config.action == Action::Move
+                {
+                    info!("remove {dup}");
+                    std::fs::remove_file(dup)?;
                 }
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod dedup_by_content_tests {
+    use super::{dedup_by_content, TaggedFile};
+    use std::collections::HashMap;
+
+    fn file(name: &str, hash: Option<[u8; 32]>) -> TaggedFile {
+        TaggedFile {
+            filename: name.to_string(),
+            tags: HashMap::new(),
+            content_hash: hash,
+        }
+    }
+
+    #[test]
+    fn files_with_the_same_hash_collapse_to_one_representative() {
+        let hash = [1u8; 32];
+        let (deduped, duplicates) = dedup_by_content(vec![
+            file("a.png", Some(hash)),
+            file("b.png", Some(hash)),
+            file("c.png", Some(hash)),
+        ]);
+
+        assert_eq!(deduped.len(), 1);
+        assert_eq!(deduped[0].filename, "a.png");
+        assert_eq!(
+            duplicates.get("a.png").unwrap(),
+            &vec!["b.png".to_string(), "c.png".to_string()]
+        );
+    }
+
+    #[test]
+    fn distinct_hashes_all_survive() {
+        let (deduped, duplicates) = dedup_by_content(vec![
+            file("a.png", Some([1u8; 32])),
+            file("b.png", Some([2u8; 32])),
+        ]);
+
+        assert_eq!(deduped.len(), 2);
+        assert!(duplicates.is_empty());
+    }
+
+    #[test]
+    fn files_with_no_hash_are_kept_and_never_deduped() {
+        let (deduped, duplicates) = dedup_by_content(vec![file("a.png", None), file("b.png", None)]);
+
+        assert_eq!(deduped.len(), 2);
+        assert!(duplicates.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod precomputed_dissim_tests {
+    use super::{ArrayAdapter, PrecomputedDissim, TaggedFile};
+
+    fn file(tags: &[(&str, f64)]) -> TaggedFile {
+        TaggedFile {
+            filename: String::new(),
+            tags: tags.iter().map(|(n, s)| (n.to_string(), *s)).collect(),
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn identical_tag_vectors_are_not_dissimilar() {
+        let files = vec![file(&[("a", 1.0)]), file(&[("a", 1.0)])];
+        let mat = PrecomputedDissim::new(&files);
+        assert_eq!(mat.get(0, 1), 0.0);
+        assert_eq!(mat.get(1, 0), 0.0);
+    }
+
+    #[test]
+    fn dissimilarity_is_one_minus_dot_product() {
+        let files = vec![file(&[("a", 0.5)]), file(&[("a", 0.3)])];
+        let mat = PrecomputedDissim::new(&files);
+        assert!((mat.get(0, 1) - 0.85).abs() < 1e-9);
+        assert!((mat.get(1, 0) - 0.85).abs() < 1e-9);
+    }
+
+    #[test]
+    fn files_sharing_no_tags_are_fully_dissimilar() {
+        let files = vec![file(&[("a", 1.0)]), file(&[("b", 1.0)])];
+        let mat = PrecomputedDissim::new(&files);
+        assert_eq!(mat.get(0, 1), 1.0);
+    }
+
+    #[test]
+    fn diagonal_is_zero() {
+        let files = vec![file(&[("a", 1.0)]), file(&[("b", 1.0)]), file(&[])];
+        let mat = PrecomputedDissim::new(&files);
+        for i in 0..files.len() {
+            assert_eq!(mat.get(i, i), 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tag_parse_tests {
+    use super::Tag;
+
+    #[test]
+    fn parses_name_and_score() {
+        let tag: Tag = "(forest:0.87)".parse().unwrap();
+        assert_eq!(tag.name, "forest");
+        assert_eq!(tag.score, 0.87);
+    }
+
+    #[test]
+    fn span_skips_leading_whitespace() {
+        let err = "  (forest 0.87)".parse::<Tag>().unwrap_err();
+        assert_eq!(err, 2..15);
+    }
+
+    #[test]
+    fn span_covers_whole_trimmed_text_when_unparseable() {
+        let err = "not-a-tag".parse::<Tag>().unwrap_err();
+        assert_eq!(err, 0..9);
+    }
+}